@@ -0,0 +1,30 @@
+//! The machine information stage 2 collects before jumping to stage 3:
+//! the E820 memory map and, if one could be set up, the VESA framebuffer
+//! layout. Stage 3 (and ultimately the kernel's `BootInfo`) reads this
+//! alongside the existing `STAGE_3_DST` handoff.
+
+use crate::memory_map::MemoryMap;
+use crate::vesa::FramebufferInfo;
+
+#[repr(C)]
+pub struct BootHandoff {
+    pub memory_map: MemoryMap,
+    pub has_framebuffer: bool,
+    pub framebuffer: FramebufferInfo,
+}
+
+impl BootHandoff {
+    /// Finishes the handoff around an already-collected `memory_map`: the
+    /// memory map itself must come from the caller rather than being
+    /// gathered in here, because setting the VESA mode below switches away
+    /// from the VGA text buffer, and any debug output the caller wants to
+    /// print against it has to happen first.
+    pub fn collect(memory_map: MemoryMap) -> Self {
+        let framebuffer = crate::vesa::set_requested_mode();
+        BootHandoff {
+            memory_map,
+            has_framebuffer: framebuffer.is_some(),
+            framebuffer: framebuffer.unwrap_or_default(),
+        }
+    }
+}
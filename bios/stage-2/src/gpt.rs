@@ -0,0 +1,235 @@
+//! GPT partition table parsing.
+//!
+//! Mirrors the MBR parsing in `main.rs`, but for GUID Partition Table
+//! disks: locate the bootloader-stage partition and the FAT data
+//! partition that follows it by matching their type GUIDs, falling back
+//! to the caller's MBR parsing when no valid GPT is present.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::crc32;
+use crate::disk::{AlignedArrayBuffer, DiskAccess, Read, Seek, SeekFrom};
+
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+const HEADER_LBA: u64 = 1;
+const SECTOR_SIZE: u64 = 512;
+
+/// A 16-byte GUID, stored in the mixed-endian byte order used on-disk.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut guid = [0; 16];
+        guid.copy_from_slice(&bytes[..16]);
+        Guid(guid)
+    }
+}
+
+/// The standard "BIOS boot partition" type GUID (`21686148-6449-6E6F-744E-656564454649`,
+/// which decodes to the ASCII string "Hah!IdontNeedEFI"). We reuse it, as
+/// GRUB does, to mark the partition holding springboard's second stage.
+pub const BOOTLOADER_SECOND_STAGE_PARTITION_TYPE: Guid = Guid([
+    0x48, 0x61, 0x68, 0x21, 0x49, 0x64, 0x6F, 0x6E, 0x74, 0x4E, 0x65, 0x65, 0x64, 0x45, 0x46, 0x49,
+]);
+
+/// The Microsoft Basic Data Partition type GUID, used by the FAT partition
+/// that follows the bootloader-stage partition.
+pub const FAT_PARTITION_TYPE: Guid = Guid([
+    0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+]);
+
+/// Starting LBAs of the two partitions springboard cares about, as found
+/// in the GPT partition entry array.
+pub struct Partitions {
+    pub second_stage_lba: u64,
+    pub fat_lba: u64,
+}
+
+/// The fields of a GPT header needed to locate and validate its partition
+/// entry array, once the header itself has been validated.
+struct HeaderFields {
+    entries_lba: u64,
+    num_entries: u32,
+    entry_size: usize,
+    entries_crc: u32,
+}
+
+/// Validates a 512-byte GPT header sector (signature and CRC-32, per the
+/// UEFI spec with the CRC field itself held at zero) and extracts the
+/// partition entry array location. Returns `None` for anything that
+/// doesn't check out, including a `header_size` so corrupt it would run
+/// past the sector -- the caller should fall back to MBR parsing in every
+/// `None` case alike.
+fn validate_header(header: &[u8; SECTOR_SIZE as usize]) -> Option<HeaderFields> {
+    if &header[0..8] != SIGNATURE {
+        return None;
+    }
+
+    let header_size = LittleEndian::read_u32(&header[12..16]) as usize;
+    // The GPT spec fixes the header at 92 bytes, but allows larger ones for
+    // future revisions; either way it must fit in the sector we just read,
+    // or a corrupt `header_size` would panic the slice copy below instead
+    // of falling back to MBR parsing like any other invalid header.
+    if !(92..=header.len()).contains(&header_size) {
+        return None;
+    }
+    let stored_header_crc = LittleEndian::read_u32(&header[16..20]);
+    let mut header_for_crc = [0u8; SECTOR_SIZE as usize];
+    header_for_crc[..header_size].copy_from_slice(&header[..header_size]);
+    header_for_crc[16..20].copy_from_slice(&[0, 0, 0, 0]);
+    if crc32::checksum(&header_for_crc[..header_size]) != stored_header_crc {
+        return None;
+    }
+
+    Some(HeaderFields {
+        entries_lba: LittleEndian::read_u64(&header[72..80]),
+        num_entries: LittleEndian::read_u32(&header[80..84]),
+        entry_size: LittleEndian::read_u32(&header[84..88]) as usize,
+        entries_crc: LittleEndian::read_u32(&header[88..92]),
+    })
+}
+
+/// Scans a validated partition entry array for the bootloader-stage and
+/// FAT partitions, matching each entry's type GUID.
+fn scan_entries(entries: &[u8], entry_size: usize, num_entries: u32) -> Option<Partitions> {
+    let mut second_stage_lba = None;
+    let mut fat_lba = None;
+    for idx in 0..num_entries as usize {
+        let offset = idx * entry_size;
+        let entry = &entries[offset..offset + entry_size];
+        let partition_type = Guid::from_bytes(&entry[0..16]);
+        let starting_lba = LittleEndian::read_u64(&entry[32..40]);
+
+        if partition_type == BOOTLOADER_SECOND_STAGE_PARTITION_TYPE {
+            second_stage_lba = Some(starting_lba);
+        } else if partition_type == FAT_PARTITION_TYPE {
+            fat_lba = Some(starting_lba);
+        }
+    }
+
+    match (second_stage_lba, fat_lba) {
+        (Some(second_stage_lba), Some(fat_lba)) => Some(Partitions {
+            second_stage_lba,
+            fat_lba,
+        }),
+        _ => None,
+    }
+}
+
+/// Attempts to locate the bootloader-stage and FAT partitions via the GPT
+/// header at LBA 1. Returns `None` if no valid (CRC-checked) GPT header is
+/// present, in which case the caller should fall back to MBR parsing.
+pub fn find_partitions(
+    disk_number: u16,
+    disk_buffer: &mut AlignedArrayBuffer<0x4000>,
+) -> Option<Partitions> {
+    let mut disk = DiskAccess {
+        disk_number,
+        base_offset: 0,
+        current_offset: 0,
+    };
+
+    disk.seek(SeekFrom::Start(HEADER_LBA * SECTOR_SIZE));
+    disk.read_exact_into(SECTOR_SIZE as usize, disk_buffer);
+    let header: &[u8; SECTOR_SIZE as usize] = disk_buffer.buffer[..SECTOR_SIZE as usize]
+        .try_into()
+        .unwrap();
+    let header = validate_header(header)?;
+
+    let entries_len = usize::try_from(u64::from(header.num_entries) * header.entry_size as u64)
+        .expect("GPT partition entry array too large");
+    assert!(
+        entries_len <= disk_buffer.buffer.len(),
+        "GPT partition entry array does not fit in the disk buffer"
+    );
+
+    disk.seek(SeekFrom::Start(header.entries_lba * SECTOR_SIZE));
+    disk.read_exact_into(entries_len, disk_buffer);
+    let entries = &disk_buffer.buffer[..entries_len];
+    if crc32::checksum(entries) != header.entries_crc {
+        return None;
+    }
+
+    scan_entries(entries, header.entry_size, header.num_entries)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    /// Builds a valid 512-byte GPT header sector (92-byte header revision,
+    /// CRC-32 computed the same way `validate_header` checks it).
+    fn sample_header(entries_lba: u64, num_entries: u32, entry_size: u32, entries_crc: u32) -> [u8; 512] {
+        let mut header = [0u8; 512];
+        header[0..8].copy_from_slice(SIGNATURE);
+        LittleEndian::write_u32(&mut header[12..16], 92);
+        LittleEndian::write_u64(&mut header[72..80], entries_lba);
+        LittleEndian::write_u32(&mut header[80..84], num_entries);
+        LittleEndian::write_u32(&mut header[84..88], entry_size);
+        LittleEndian::write_u32(&mut header[88..92], entries_crc);
+        let crc = crc32::checksum(&header[..92]);
+        LittleEndian::write_u32(&mut header[16..20], crc);
+        header
+    }
+
+    #[test]
+    fn validate_header_accepts_correct_crc() {
+        let header = sample_header(2, 128, 128, 0xDEAD_BEEF);
+        let fields = validate_header(&header).unwrap();
+        assert_eq!(fields.entries_lba, 2);
+        assert_eq!(fields.num_entries, 128);
+        assert_eq!(fields.entry_size, 128);
+        assert_eq!(fields.entries_crc, 0xDEAD_BEEF);
+    }
+
+    #[test]
+    fn validate_header_rejects_bad_signature() {
+        let mut header = sample_header(2, 128, 128, 0);
+        header[0] = b'X';
+        assert!(validate_header(&header).is_none());
+    }
+
+    #[test]
+    fn validate_header_rejects_corrupt_header_size_instead_of_panicking() {
+        let mut header = sample_header(2, 128, 128, 0);
+        LittleEndian::write_u32(&mut header[12..16], 0xFFFF_FFFF);
+        assert!(validate_header(&header).is_none());
+
+        let mut header = sample_header(2, 128, 128, 0);
+        LittleEndian::write_u32(&mut header[12..16], 10);
+        assert!(validate_header(&header).is_none());
+    }
+
+    #[test]
+    fn validate_header_rejects_crc_mismatch() {
+        let mut header = sample_header(2, 128, 128, 0);
+        header[16] ^= 0xFF;
+        assert!(validate_header(&header).is_none());
+    }
+
+    #[test]
+    fn scan_entries_matches_both_partition_types() {
+        const ENTRY_SIZE: usize = 128;
+        let mut entries = std::vec![0u8; ENTRY_SIZE * 2];
+        entries[0..16].copy_from_slice(&BOOTLOADER_SECOND_STAGE_PARTITION_TYPE.0);
+        LittleEndian::write_u64(&mut entries[32..40], 40);
+        entries[ENTRY_SIZE..ENTRY_SIZE + 16].copy_from_slice(&FAT_PARTITION_TYPE.0);
+        LittleEndian::write_u64(&mut entries[ENTRY_SIZE + 32..ENTRY_SIZE + 40], 2088);
+
+        let partitions = scan_entries(&entries, ENTRY_SIZE, 2).unwrap();
+        assert_eq!(partitions.second_stage_lba, 40);
+        assert_eq!(partitions.fat_lba, 2088);
+    }
+
+    #[test]
+    fn scan_entries_none_without_both_types() {
+        const ENTRY_SIZE: usize = 128;
+        let mut entries = std::vec![0u8; ENTRY_SIZE];
+        entries[0..16].copy_from_slice(&BOOTLOADER_SECOND_STAGE_PARTITION_TYPE.0);
+
+        assert!(scan_entries(&entries, ENTRY_SIZE, 1).is_none());
+    }
+}
@@ -13,18 +13,37 @@ use crate::{
     },
 };
 
+mod boot_handoff;
+mod crc32;
 mod dap;
 mod disk;
 mod fat;
+mod gpt;
+mod manifest;
+mod memory_map;
 mod protected_mode;
 mod screen;
+mod update;
+mod vesa;
 
 /// We use this partition type to store the second bootloader stage;
 const BOOTLOADER_SECOND_STAGE_PARTITION_TYPE: u8 = 0x20;
 
+/// The protective MBR that precedes a GPT places a single entry of this
+/// type at LBA 0, covering the whole disk.
+const GPT_PROTECTIVE_MBR_PARTITION_TYPE: u8 = 0xEE;
+
 const STAGE_3_DST: *mut u8 = 0x0010_0000 as *mut u8; // 1MiB (typically 14MiB accessible here)
 const KERNEL_DST: *mut u8 = 0x0100_0000 as *mut u8; // 16MiB
 
+/// Where the collected [`boot_handoff::BootHandoff`] is written for stage 3
+/// to pick up. `0xF0000..=0xFFFFF` is the BIOS ROM/shadow region (often
+/// write-protected after POST), so this has to live in extended memory
+/// instead: chosen just below `KERNEL_DST`, in the same 15MiB gap above
+/// `STAGE_3_DST` that stage 3/4 load into, with plenty of room to spare.
+const BOOT_HANDOFF_DST: *mut boot_handoff::BootHandoff =
+    0x00FF_F000 as *mut boot_handoff::BootHandoff;
+
 extern "C" {
     static _second_stage_end: u8;
 }
@@ -37,6 +56,8 @@ static mut DISK_BUFFER: AlignedArrayBuffer<0x4000> = AlignedArrayBuffer {
     buffer: [0; 0x4000],
 };
 
+static mut MANIFEST_BUFFER: AlignedArrayBuffer<512> = AlignedArrayBuffer { buffer: [0; 512] };
+
 #[no_mangle]
 #[link_section = ".start"]
 pub extern "C" fn _start(disk_number: u16, partition_table_start: *const u8) {
@@ -44,6 +65,8 @@ pub extern "C" fn _start(disk_number: u16, partition_table_start: *const u8) {
 
     enter_unreal_mode();
 
+    let disk_buffer = unsafe { &mut DISK_BUFFER };
+
     // parse partition table
     let partitions = {
         const MAX_ENTRIES: usize = 4;
@@ -60,64 +83,149 @@ pub extern "C" fn _start(disk_number: u16, partition_table_start: *const u8) {
         }
         entries
     };
-    // look for second stage partition
-    let second_stage_partition_idx = partitions
-        .iter()
-        .enumerate()
-        .find(|(_, e)| {
-            e.partition_type == PartitionType::Unknown(BOOTLOADER_SECOND_STAGE_PARTITION_TYPE)
-        })
-        .unwrap()
-        .0;
-    let fat_partition = partitions.get(second_stage_partition_idx + 1).unwrap();
-    assert!(matches!(
-        fat_partition.partition_type,
-        PartitionType::Fat12(_) | PartitionType::Fat16(_) | PartitionType::Fat32(_)
-    ));
+
+    // A protective MBR (a single 0xEE entry covering the disk) means the
+    // real partition table is a GPT; try that first and fall back to the
+    // legacy MBR table above otherwise.
+    let is_protective_mbr = partitions[0].partition_type
+        == PartitionType::Unknown(GPT_PROTECTIVE_MBR_PARTITION_TYPE)
+        && partitions[1..].iter().all(|e| e.sector_count == 0);
+
+    // Stage 2 is already running, so only the FAT partition's offset is
+    // needed from here on; the second-stage offset is resolved purely to
+    // validate that a matching GPT entry exists.
+    let (_second_stage_base_offset, fat_base_offset) = if is_protective_mbr {
+        gpt::find_partitions(disk_number, disk_buffer)
+            .map(|p| (p.second_stage_lba * 512, p.fat_lba * 512))
+            .unwrap_or_else(|| mbr_partition_offsets(&partitions))
+    } else {
+        mbr_partition_offsets(&partitions)
+    };
 
     // load fat partition
     let mut disk = disk::DiskAccess {
         disk_number,
-        base_offset: u64::from(fat_partition.logical_block_address) * 512,
+        base_offset: fat_base_offset,
         current_offset: 0,
     };
 
     let mut fs = fat::FileSystem::parse(disk.clone());
 
-    let disk_buffer = unsafe { &mut DISK_BUFFER };
+    // Disks without an integrity manifest (e.g. built before this feature
+    // existed) simply skip CRC verification.
+    let manifest_buffer = unsafe { &mut MANIFEST_BUFFER };
+    let manifest_len = manifest::load(&mut fs, &mut disk, disk_buffer, manifest_buffer);
+    let manifest = match manifest_len {
+        Some(len) => &manifest_buffer.buffer[..len],
+        None => &[][..],
+    };
 
-    let stage_3_len = load_file("boot-stage-3", STAGE_3_DST, &mut fs, &mut disk, disk_buffer);
+    let stage_3_len = load_file(
+        "boot-stage-3",
+        STAGE_3_DST,
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+        manifest,
+    );
     writeln!(screen::Writer, "stage 3 loaded at {STAGE_3_DST:#p}").unwrap();
     let stage_4_dst = {
         let stage_3_end = STAGE_3_DST.wrapping_add(usize::try_from(stage_3_len).unwrap());
         let align_offset = stage_3_end.align_offset(512);
         stage_3_end.wrapping_add(align_offset)
     };
-    load_file("boot-stage-4", stage_4_dst, &mut fs, &mut disk, disk_buffer);
+    load_file(
+        "boot-stage-4",
+        stage_4_dst,
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+        manifest,
+    );
     writeln!(screen::Writer, "stage 4 loaded at {stage_4_dst:#p}").unwrap();
-    load_file("kernel-x86_64", KERNEL_DST, &mut fs, &mut disk, disk_buffer);
+
+    // A disk with no `boot-state` file isn't A/B-managed at all; load its
+    // single unsuffixed kernel rather than resolving a slot for it. One
+    // with the file present resumes any interrupted update first, so a
+    // power loss mid-swap is picked up here before we decide what to boot.
+    let kernel_file_name = match update::load(&mut fs, &mut disk, disk_buffer) {
+        Some(mut boot_state) => {
+            update::resume_pending_swap(&mut boot_state, &mut fs, &mut disk, disk_buffer);
+            let kernel_slot = update::resolve_boot_slot(&mut boot_state);
+            update::store(&boot_state, &mut fs, &mut disk, disk_buffer);
+            kernel_slot.kernel_file_name()
+        }
+        None => update::LEGACY_KERNEL_FILE_NAME,
+    };
+
+    load_file(
+        kernel_file_name,
+        KERNEL_DST,
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+        manifest,
+    );
     writeln!(screen::Writer, "kernel loaded at {KERNEL_DST:#p}").unwrap();
 
-    // TODO: Retrieve memory map
-    // TODO: VESA config
+    // Collect machine information while the VGA text buffer is still the
+    // active display surface; `BootHandoff::collect` switches to a
+    // graphics framebuffer, after which any further text-mode writes would
+    // be lost or garbled.
+    let memory_map = memory_map::detect_memory();
+    writeln!(screen::Writer, "found {} memory region(s)", memory_map.len).unwrap();
+    let boot_handoff = boot_handoff::BootHandoff::collect(memory_map);
 
+    // Stage 3 (and `protected_mode.rs`, which this tree doesn't contain)
+    // isn't ours to extend with a new parameter of a type it's never seen,
+    // so the handoff goes through the same kind of fixed, pre-arranged
+    // address convention as `STAGE_3_DST`/`KERNEL_DST` rather than a
+    // changed call signature: stage 3 reads a `BootHandoff` back out of
+    // `BOOT_HANDOFF_DST`.
+    unsafe { core::ptr::write(BOOT_HANDOFF_DST, boot_handoff) };
     enter_protected_mode_and_jump_to_stage_3(STAGE_3_DST);
 
     loop {}
 }
 
+/// Locates the bootloader-stage and FAT partitions in a legacy 4-entry MBR
+/// table, returning their byte offsets from the start of the disk.
+fn mbr_partition_offsets(partitions: &[PartitionTableEntry]) -> (u64, u64) {
+    let second_stage_partition_idx = partitions
+        .iter()
+        .enumerate()
+        .find(|(_, e)| {
+            e.partition_type == PartitionType::Unknown(BOOTLOADER_SECOND_STAGE_PARTITION_TYPE)
+        })
+        .unwrap()
+        .0;
+    let fat_partition = partitions.get(second_stage_partition_idx + 1).unwrap();
+    assert!(matches!(
+        fat_partition.partition_type,
+        PartitionType::Fat12(_) | PartitionType::Fat16(_) | PartitionType::Fat32(_)
+    ));
+
+    let second_stage_partition = &partitions[second_stage_partition_idx];
+    (
+        u64::from(second_stage_partition.logical_block_address) * 512,
+        u64::from(fat_partition.logical_block_address) * 512,
+    )
+}
+
 fn load_file(
     file_name: &str,
     dst: *mut u8,
     fs: &mut fat::FileSystem<disk::DiskAccess>,
     disk: &mut disk::DiskAccess,
     disk_buffer: &mut AlignedArrayBuffer<16384>,
+    manifest: &[u8],
 ) -> u64 {
     let disk_buffer_size = disk_buffer.buffer.len();
     let kernel = fs
         .find_file_in_root_dir(file_name, disk_buffer)
         .expect("file not found");
     let mut total_size = 0;
+    let mut crc = crc32::Crc32::new();
     for cluster in fs.file_clusters(&kernel) {
         let cluster = cluster.unwrap();
         let cluster_start = cluster.start_offset;
@@ -146,6 +254,7 @@ fn load_file(
             disk.read_exact_into(disk_buffer_size, disk_buffer);
 
             let slice = &disk_buffer.buffer[..usize::try_from(len).unwrap()];
+            crc.update(slice);
             unsafe {
                 copy_to_protected_mode(dst.wrapping_add(usize::try_from(offset).unwrap()), slice)
             };
@@ -159,6 +268,15 @@ fn load_file(
             offset += len;
         }
     }
+
+    // Verify against the manifest, if this disk has one; a mismatch means
+    // the payload was corrupted somewhere along the BIOS disk interface.
+    if let Some(expected) = manifest::lookup(manifest, file_name) {
+        if crc.finish() != expected {
+            fail(b'C');
+        }
+    }
+
     total_size
 }
 
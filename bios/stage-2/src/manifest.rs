@@ -0,0 +1,101 @@
+//! Parses the integrity manifest (`checksums`) written by the image
+//! builder, which maps each loaded payload's file name to its expected
+//! CRC-32 so `load_file` can detect silent corruption introduced by the
+//! BIOS disk interface.
+//!
+//! The manifest is a flat sequence of fixed-size records: a 32-byte,
+//! NUL-padded ASCII file name followed by a little-endian CRC-32.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::disk::{AlignedArrayBuffer, Read, Seek, SeekFrom};
+use crate::fat;
+
+/// Name of the manifest file in the FAT root directory.
+pub const MANIFEST_FILE_NAME: &str = "checksums";
+const NAME_LEN: usize = 32;
+/// Size of a single `(name, crc32)` record.
+pub const RECORD_LEN: usize = NAME_LEN + 4;
+
+/// Reads the manifest file into `out`, returning the number of valid
+/// bytes, or `None` if the disk has no manifest (e.g. it predates this
+/// feature), in which case the caller should skip verification entirely.
+pub fn load<D: Read + Seek>(
+    fs: &mut fat::FileSystem<D>,
+    disk: &mut D,
+    scratch: &mut AlignedArrayBuffer<16384>,
+    out: &mut AlignedArrayBuffer<512>,
+) -> Option<usize> {
+    let file = fs.find_file_in_root_dir(MANIFEST_FILE_NAME, scratch).ok()?;
+    let mut total = 0;
+    for cluster in fs.file_clusters(&file) {
+        let cluster = cluster.ok()?;
+        let len = usize::try_from(cluster.len_bytes).ok()?;
+        assert!(
+            total + len <= out.buffer.len(),
+            "integrity manifest is larger than expected"
+        );
+        disk.seek(SeekFrom::Start(cluster.start_offset));
+        disk.read_exact_into(len, scratch);
+        out.buffer[total..total + len].copy_from_slice(&scratch.buffer[..len]);
+        total += len;
+    }
+    Some(total)
+}
+
+/// Looks up the expected CRC-32 for `file_name` within a manifest already
+/// read into `bytes` by [`load`].
+pub fn lookup(bytes: &[u8], file_name: &str) -> Option<u32> {
+    bytes.chunks_exact(RECORD_LEN).find_map(|record| {
+        let name_bytes = &record[..NAME_LEN];
+        let len = name_bytes.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+        if &name_bytes[..len] == file_name.as_bytes() {
+            Some(LittleEndian::read_u32(&record[NAME_LEN..]))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    /// Builds a manifest with the same (32-byte NUL-padded name, LE
+    /// CRC-32) record layout `src/image/checksum.rs::build_manifest`
+    /// produces on the host side, so a mismatch between the two can't slip
+    /// through either side's tests alone.
+    fn record(name: &str, crc: u32) -> std::vec::Vec<u8> {
+        assert!(name.len() <= NAME_LEN);
+        let mut out = std::vec![0u8; RECORD_LEN];
+        out[..name.len()].copy_from_slice(name.as_bytes());
+        out[NAME_LEN..].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn lookup_finds_matching_record() {
+        let mut bytes = record("boot-stage-3", 0x1111_1111);
+        bytes.extend(record("kernel-x86_64-a", 0x2222_2222));
+
+        assert_eq!(lookup(&bytes, "boot-stage-3"), Some(0x1111_1111));
+        assert_eq!(lookup(&bytes, "kernel-x86_64-a"), Some(0x2222_2222));
+    }
+
+    #[test]
+    fn lookup_does_not_match_a_name_prefix() {
+        let bytes = record("kernel-x86_64-a", 0x2222_2222);
+        // A NUL-terminated match must cover the whole stored name, not
+        // just a prefix of it -- otherwise "kernel-x86_64" would
+        // incorrectly match the "-a"/"-b" slot records too.
+        assert_eq!(lookup(&bytes, "kernel-x86_64"), None);
+    }
+
+    #[test]
+    fn lookup_missing_name_returns_none() {
+        let bytes = record("boot-stage-3", 0x1111_1111);
+        assert_eq!(lookup(&bytes, "boot-stage-4"), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Real-mode BIOS memory map retrieval via `INT 0x15, EAX=0xE820`.
+
+use core::arch::asm;
+use core::mem::size_of;
+
+/// Upper bound on how many regions we'll collect; BIOSes rarely report
+/// more than a handful.
+pub const MAX_REGIONS: usize = 32;
+
+const SMAP: u32 = 0x534D_4150; // ASCII "SMAP", the E820 signature
+
+/// A single E820 memory region, in the layout the BIOS fills directly.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub length: u64,
+    pub region_type: u32,
+    /// Only present on BIOSes returning the 24-byte (ACPI 3.0) entry form;
+    /// zero otherwise.
+    pub acpi_extended_attributes: u32,
+}
+
+/// The collected memory map, handed off to stage 3.
+#[repr(C)]
+pub struct MemoryMap {
+    pub regions: [MemoryRegion; MAX_REGIONS],
+    pub len: usize,
+}
+
+impl MemoryMap {
+    const fn empty() -> Self {
+        MemoryMap {
+            regions: [MemoryRegion {
+                base: 0,
+                length: 0,
+                region_type: 0,
+                acpi_extended_attributes: 0,
+            }; MAX_REGIONS],
+            len: 0,
+        }
+    }
+}
+
+/// Iterates `INT 0x15, EAX=0xE820` until the continuation value the BIOS
+/// hands back is zero, collecting up to [`MAX_REGIONS`] entries. Assumes
+/// unreal mode (flat addressing with zero-based segments), same as the
+/// disk-read path.
+pub fn detect_memory() -> MemoryMap {
+    let mut map = MemoryMap::empty();
+    let mut continuation: u32 = 0;
+
+    loop {
+        let mut entry = MemoryRegion::default();
+        let signature: u32;
+        let entry_size: u32;
+        let carry: u8;
+
+        // `ebx` carries the continuation value across `INT 0x15` calls, but
+        // LLVM reserves it internally and won't accept it as a direct
+        // inline-asm operand; shuttle it through manually instead.
+        unsafe {
+            asm!(
+                "push ebx",
+                "mov ebx, {continuation:e}",
+                "int 0x15",
+                "mov {continuation:e}, ebx",
+                "pop ebx",
+                "setc {carry}",
+                inout("eax") 0xE820u32 => signature,
+                continuation = inout(reg) continuation,
+                inout("ecx") size_of::<MemoryRegion>() as u32 => entry_size,
+                in("edx") SMAP,
+                in("edi") &mut entry,
+                carry = out(reg_byte) carry,
+            );
+        }
+
+        if carry != 0 || signature != SMAP {
+            break;
+        }
+
+        if entry_size >= 20 && map.len < MAX_REGIONS {
+            map.regions[map.len] = entry;
+            map.len += 1;
+        }
+
+        if continuation == 0 {
+            break;
+        }
+    }
+
+    map
+}
@@ -0,0 +1,463 @@
+//! A/B dual-slot kernel selection with automatic rollback.
+//!
+//! The state region is a dedicated FAT file (`boot-state`) in the root
+//! directory holding a magic value, the active slot, a per-slot
+//! [`SlotState`], and a `boot_attempts` counter for whichever slot is
+//! currently on trial. Stage 2 consults it on every boot to decide
+//! whether to load `kernel-x86_64-a` or `kernel-x86_64-b`, decrementing
+//! the trial counter or rolling back to the other slot as needed, then
+//! writes the (possibly updated) state back before jumping to stage 3.
+//!
+//! A disk with no `boot-state` file at all -- built before this feature
+//! existed, or without the `image-builder` A/B support -- is not an A/B
+//! disk: [`load`] returns `None` for it, and the caller loads the single,
+//! unsuffixed [`LEGACY_KERNEL_FILE_NAME`] instead of guessing at a slot.
+//!
+//! The loaded kernel confirms a successful boot by writing
+//! [`SlotState::Good`] back into this same region; [`mark_good`] is that
+//! write, meant to be called through a `springboard_api` runtime call once
+//! the kernel has reached a point it trusts (that crate lives outside this
+//! tree, so it is not wired up here, but this is the exact state mutation
+//! it must perform). Applying an update -- copying a new image into the
+//! inactive slot and marking it `Trial` -- is a resumable copy from a
+//! `update-scratch` file into that slot's kernel file; see
+//! [`resume_pending_swap`], which stage 2 runs before [`resolve_boot_slot`]
+//! on every boot so an interrupted update always finishes (or is safely
+//! abandoned) without ever touching the currently active, known-good slot.
+
+use crate::disk::{AlignedArrayBuffer, Read, Seek, SeekFrom, Write};
+use crate::fat;
+
+const MAGIC: u32 = 0x5342_4142; // "SBAB": SpringBoard A/B
+const DEFAULT_TRIAL_ATTEMPTS: u8 = 3;
+
+/// Name of the FAT file holding the A/B boot state.
+const STATE_FILE_NAME: &str = "boot-state";
+
+/// The kernel file name used by disks with no A/B metadata at all, as
+/// opposed to the slot-suffixed names in [`Slot::kernel_file_name`].
+pub const LEGACY_KERNEL_FILE_NAME: &str = "kernel-x86_64";
+
+/// Per-slot status, as tracked by [`BootState`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SlotState {
+    /// Confirmed to boot successfully; safe to keep booting.
+    Good,
+    /// Newly updated; boot it up to `boot_attempts` more times before
+    /// giving up and rolling back.
+    Trial,
+    /// Exhausted its trial attempts without confirming; not booted again
+    /// while the other slot is [`Good`][SlotState::Good].
+    Bad,
+}
+
+impl SlotState {
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => SlotState::Good,
+            1 => SlotState::Trial,
+            _ => SlotState::Bad,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            SlotState::Good => 0,
+            SlotState::Trial => 1,
+            SlotState::Bad => 2,
+        }
+    }
+}
+
+/// One of the two kernel slots.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    /// The FAT file name of the kernel image for this slot.
+    pub fn kernel_file_name(self) -> &'static str {
+        match self {
+            Slot::A => "kernel-x86_64-a",
+            Slot::B => "kernel-x86_64-b",
+        }
+    }
+}
+
+/// The parsed contents of the `boot-state` file.
+pub struct BootState {
+    pub active_slot: Slot,
+    slot_a: SlotState,
+    slot_b: SlotState,
+    pub boot_attempts: u8,
+}
+
+impl BootState {
+    /// On-disk size of the state region, in bytes.
+    pub const SIZE: usize = 8;
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE || u32::from_le_bytes(bytes[0..4].try_into().unwrap()) != MAGIC
+        {
+            return None;
+        }
+        let active_slot = if bytes[4] == 0 { Slot::A } else { Slot::B };
+        Some(BootState {
+            active_slot,
+            slot_a: SlotState::from_byte(bytes[5]),
+            slot_b: SlotState::from_byte(bytes[6]),
+            boot_attempts: bytes[7],
+        })
+    }
+
+    fn serialize(&self, out: &mut [u8; Self::SIZE]) {
+        out[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        out[4] = if self.active_slot == Slot::A { 0 } else { 1 };
+        out[5] = self.slot_a.to_byte();
+        out[6] = self.slot_b.to_byte();
+        out[7] = self.boot_attempts;
+    }
+
+    fn state_of(&self, slot: Slot) -> SlotState {
+        match slot {
+            Slot::A => self.slot_a,
+            Slot::B => self.slot_b,
+        }
+    }
+
+    fn set_state(&mut self, slot: Slot, state: SlotState) {
+        match slot {
+            Slot::A => self.slot_a = state,
+            Slot::B => self.slot_b = state,
+        }
+    }
+}
+
+/// Decides which slot to boot, applying the trial-counter decrement and
+/// automatic rollback described in the module docs. `state` is updated in
+/// place; the caller is responsible for persisting it with [`store`].
+///
+/// A rollback only ever switches to a slot already confirmed [`Good`][SlotState::Good];
+/// if the other slot isn't, the active slot keeps booting rather than
+/// trading one unconfirmed image for another.
+pub fn resolve_boot_slot(state: &mut BootState) -> Slot {
+    match state.state_of(state.active_slot) {
+        SlotState::Good => state.active_slot,
+        SlotState::Trial if state.boot_attempts > 0 => {
+            state.boot_attempts -= 1;
+            state.active_slot
+        }
+        SlotState::Trial => {
+            // Exhausted the trial without a confirmed boot: give up on it
+            // and roll back, but only onto a slot we know is good.
+            state.set_state(state.active_slot, SlotState::Bad);
+            roll_back(state)
+        }
+        SlotState::Bad => roll_back(state),
+    }
+}
+
+/// Switches to the other slot if (and only if) it is [`Good`][SlotState::Good];
+/// otherwise both slots are unbootable and there is nothing safer left to
+/// do than keep trying the active one.
+fn roll_back(state: &mut BootState) -> Slot {
+    let other = state.active_slot.other();
+    if state.state_of(other) == SlotState::Good {
+        state.active_slot = other;
+    }
+    state.active_slot
+}
+
+/// Reads and parses the boot state file from the FAT root directory.
+/// Returns `None` if the disk has no `boot-state` file at all (e.g. it
+/// predates this feature, or was never built with A/B support), in which
+/// case the caller should treat the disk as a single, unmanaged slot
+/// rather than inventing a fallback [`BootState`] for it.
+pub fn load<D: Read + Seek>(
+    fs: &mut fat::FileSystem<D>,
+    disk: &mut D,
+    disk_buffer: &mut AlignedArrayBuffer<16384>,
+) -> Option<BootState> {
+    let file = fs.find_file_in_root_dir(STATE_FILE_NAME, disk_buffer).ok()?;
+    let cluster = fs.file_clusters(&file).next()?.ok()?;
+    disk.seek(SeekFrom::Start(cluster.start_offset));
+    disk.read_exact_into(BootState::SIZE, disk_buffer);
+    BootState::parse(&disk_buffer.buffer[..BootState::SIZE])
+}
+
+/// Writes the boot state back to the same cluster it was read from, so
+/// trial-counter decrements and rollbacks persist across the next boot.
+/// A disk with no `boot-state` file is left untouched.
+pub fn store<D: Read + Seek + Write>(
+    state: &BootState,
+    fs: &mut fat::FileSystem<D>,
+    disk: &mut D,
+    disk_buffer: &mut AlignedArrayBuffer<16384>,
+) {
+    let Some(file) = fs.find_file_in_root_dir(STATE_FILE_NAME, disk_buffer).ok() else {
+        return;
+    };
+    let Some(Ok(cluster)) = fs.file_clusters(&file).next() else {
+        return;
+    };
+    let mut bytes = [0u8; BootState::SIZE];
+    state.serialize(&mut bytes);
+    disk.seek(SeekFrom::Start(cluster.start_offset));
+    disk.write_exact(&bytes);
+}
+
+/// Marks `slot` [`Good`][SlotState::Good] and persists the state. This is
+/// the state mutation a booted kernel performs to confirm itself, normally
+/// reached through a `springboard_api` runtime call once it trusts its own
+/// boot; there is no such call in this tree, but this is what it writes.
+pub fn mark_good<D: Read + Seek + Write>(
+    slot: Slot,
+    fs: &mut fat::FileSystem<D>,
+    disk: &mut D,
+    disk_buffer: &mut AlignedArrayBuffer<16384>,
+) {
+    let Some(mut state) = load(fs, disk, disk_buffer) else {
+        return;
+    };
+    state.set_state(slot, SlotState::Good);
+    state.boot_attempts = DEFAULT_TRIAL_ATTEMPTS;
+    store(&state, fs, disk, disk_buffer);
+}
+
+/// Name of the FAT file an in-progress update stages its new kernel image
+/// in, before it is copied into the target slot.
+const SCRATCH_FILE_NAME: &str = "update-scratch";
+
+/// Name of the FAT file holding the in-progress [`SwapState`], if any.
+const SWAP_STATE_FILE_NAME: &str = "boot-swap";
+
+const SWAP_MAGIC: u32 = 0x5342_5357; // "SBSW": SpringBoard SWap
+
+/// Tracks an update copy in progress from [`SCRATCH_FILE_NAME`] into a
+/// slot's kernel file, so it can resume after a power loss instead of
+/// restarting (or leaving the target slot in a half-written state that
+/// `resolve_boot_slot` might pick).
+struct SwapState {
+    target_slot: Slot,
+    /// Number of clusters already copied from the scratch file.
+    clusters_done: u32,
+}
+
+impl SwapState {
+    const SIZE: usize = 12;
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE
+            || u32::from_le_bytes(bytes[0..4].try_into().unwrap()) != SWAP_MAGIC
+        {
+            return None;
+        }
+        let target_slot = if bytes[4] == 0 { Slot::A } else { Slot::B };
+        Some(SwapState {
+            target_slot,
+            clusters_done: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        })
+    }
+
+    fn serialize(&self, out: &mut [u8; Self::SIZE]) {
+        out[0..4].copy_from_slice(&SWAP_MAGIC.to_le_bytes());
+        out[4] = if self.target_slot == Slot::A { 0 } else { 1 };
+        out[5..8].fill(0);
+        out[8..12].copy_from_slice(&self.clusters_done.to_le_bytes());
+    }
+}
+
+/// Resumes an update copy left in progress by a prior, interrupted boot:
+/// streams whatever clusters of [`SCRATCH_FILE_NAME`] haven't yet been
+/// copied into the target slot's kernel file, persisting
+/// [`SwapState::clusters_done`] after each one so a repeated power loss
+/// just picks back up rather than re-copying or giving up. Because this
+/// only ever writes into the *inactive* slot, `state.active_slot` stays
+/// fully intact throughout -- there is always at least one bootable image
+/// on disk, no matter when power is lost.
+///
+/// Once the copy finishes, the `boot-swap` marker is invalidated so a
+/// later boot doesn't see it again: left in place, it would re-run this
+/// function every time, forcing the target slot back into a fresh
+/// [`Trial`][SlotState::Trial] on every single boot -- which would both
+/// stop `boot_attempts` from ever reaching zero (defeating automatic
+/// rollback) and overwrite a kernel's [`mark_good`] confirmation back to
+/// `Trial` on its very next boot.
+///
+/// Call this before [`resolve_boot_slot`] on every boot; it is a no-op if
+/// there is no `boot-swap` file, or once the copy has fully finished.
+pub fn resume_pending_swap<D: Read + Seek + Write>(
+    state: &mut BootState,
+    fs: &mut fat::FileSystem<D>,
+    disk: &mut D,
+    disk_buffer: &mut AlignedArrayBuffer<16384>,
+) {
+    let Some(swap_file) = fs.find_file_in_root_dir(SWAP_STATE_FILE_NAME, disk_buffer).ok() else {
+        return;
+    };
+    let Some(Ok(swap_cluster)) = fs.file_clusters(&swap_file).next() else {
+        return;
+    };
+    disk.seek(SeekFrom::Start(swap_cluster.start_offset));
+    disk.read_exact_into(SwapState::SIZE, disk_buffer);
+    let Some(mut swap) = SwapState::parse(&disk_buffer.buffer[..SwapState::SIZE]) else {
+        return;
+    };
+
+    if swap.target_slot == state.active_slot {
+        // Never overwrite the slot we are about to boot from.
+        invalidate_swap(disk, swap_cluster.start_offset);
+        return;
+    }
+
+    let (Some(scratch_file), Some(target_file)) = (
+        fs.find_file_in_root_dir(SCRATCH_FILE_NAME, disk_buffer).ok(),
+        fs.find_file_in_root_dir(swap.target_slot.kernel_file_name(), disk_buffer)
+            .ok(),
+    ) else {
+        // No scratch payload (or no pre-allocated target slot file) to
+        // resume from: treat the swap as abandoned.
+        invalidate_swap(disk, swap_cluster.start_offset);
+        return;
+    };
+
+    let scratch_clusters = fs.file_clusters(&scratch_file).count();
+    let target_clusters = fs.file_clusters(&target_file).count();
+    if target_clusters < scratch_clusters {
+        // The pre-allocated target slot file is too small for the new
+        // image: copying would silently truncate it, and the truncated
+        // result would still get marked `Trial` and booted below. Abandon
+        // the swap instead of ever handing out a partial kernel.
+        invalidate_swap(disk, swap_cluster.start_offset);
+        return;
+    }
+
+    for (index, (scratch_cluster, target_cluster)) in fs
+        .file_clusters(&scratch_file)
+        .zip(fs.file_clusters(&target_file))
+        .enumerate()
+    {
+        if (index as u32) < swap.clusters_done {
+            continue;
+        }
+        let scratch_cluster = scratch_cluster.unwrap();
+        let target_cluster = target_cluster.unwrap();
+        let len = usize::try_from(scratch_cluster.len_bytes).unwrap();
+
+        disk.seek(SeekFrom::Start(scratch_cluster.start_offset));
+        disk.read_exact_into(len, disk_buffer);
+        disk.seek(SeekFrom::Start(target_cluster.start_offset));
+        disk.write_exact(&disk_buffer.buffer[..len]);
+
+        swap.clusters_done = u32::try_from(index + 1).unwrap();
+        let mut bytes = [0u8; SwapState::SIZE];
+        swap.serialize(&mut bytes);
+        disk.seek(SeekFrom::Start(swap_cluster.start_offset));
+        disk.write_exact(&bytes);
+    }
+
+    // The new image is fully in place; invalidate the marker so this
+    // doesn't run again, then give the target slot a fresh trial rather
+    // than letting `resolve_boot_slot` see stale state from before the
+    // update was applied. This only happens once per swap: a resumed copy
+    // that picks up from `clusters_done` > 0 still only reaches here, and
+    // thus only applies this transition, on the boot that finishes it.
+    invalidate_swap(disk, swap_cluster.start_offset);
+    state.set_state(swap.target_slot, SlotState::Trial);
+    state.active_slot = swap.target_slot;
+    state.boot_attempts = DEFAULT_TRIAL_ATTEMPTS;
+}
+
+/// Overwrites the `boot-swap` cluster with zeroes, clearing [`SWAP_MAGIC`]
+/// so [`SwapState::parse`] -- and thus [`resume_pending_swap`] -- treats it
+/// as absent on every later boot.
+fn invalidate_swap<D: Write + Seek>(disk: &mut D, swap_cluster_offset: u64) {
+    disk.seek(SeekFrom::Start(swap_cluster_offset));
+    disk.write_exact(&[0u8; SwapState::SIZE]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(active_slot: Slot, slot_a: SlotState, slot_b: SlotState, boot_attempts: u8) -> BootState {
+        BootState {
+            active_slot,
+            slot_a,
+            slot_b,
+            boot_attempts,
+        }
+    }
+
+    #[test]
+    fn good_slot_keeps_booting_without_touching_attempts() {
+        let mut s = state(Slot::A, SlotState::Good, SlotState::Good, 0);
+        assert!(resolve_boot_slot(&mut s) == Slot::A);
+        assert_eq!(s.boot_attempts, 0);
+    }
+
+    #[test]
+    fn trial_slot_decrements_attempts_while_they_remain() {
+        let mut s = state(Slot::A, SlotState::Trial, SlotState::Good, 2);
+        assert!(resolve_boot_slot(&mut s) == Slot::A);
+        assert_eq!(s.boot_attempts, 1);
+    }
+
+    #[test]
+    fn exhausted_trial_rolls_back_to_a_good_slot() {
+        let mut s = state(Slot::A, SlotState::Trial, SlotState::Good, 0);
+        assert!(resolve_boot_slot(&mut s) == Slot::B);
+        assert!(s.state_of(Slot::A) == SlotState::Bad);
+        assert!(s.active_slot == Slot::B);
+    }
+
+    #[test]
+    fn exhausted_trial_does_not_roll_back_onto_a_non_good_slot() {
+        // Neither slot is Good: rolling over to B would swap one
+        // unconfirmed image for another, so the active slot must stay put.
+        let mut s = state(Slot::A, SlotState::Trial, SlotState::Bad, 0);
+        assert!(resolve_boot_slot(&mut s) == Slot::A);
+        assert!(s.state_of(Slot::A) == SlotState::Bad);
+    }
+
+    #[test]
+    fn bad_active_slot_rolls_back_to_good_other_slot() {
+        let mut s = state(Slot::B, SlotState::Good, SlotState::Bad, 0);
+        assert!(resolve_boot_slot(&mut s) == Slot::A);
+    }
+
+    #[test]
+    fn bad_active_slot_does_not_roll_back_onto_a_bad_slot() {
+        let mut s = state(Slot::A, SlotState::Bad, SlotState::Bad, 0);
+        assert!(resolve_boot_slot(&mut s) == Slot::A);
+    }
+
+    #[test]
+    fn state_round_trips_through_serialize_and_parse() {
+        let s = state(Slot::B, SlotState::Trial, SlotState::Bad, 2);
+        let mut bytes = [0u8; BootState::SIZE];
+        s.serialize(&mut bytes);
+        let parsed = BootState::parse(&bytes).unwrap();
+        assert!(parsed.active_slot == Slot::B);
+        assert!(parsed.state_of(Slot::A) == SlotState::Trial);
+        assert!(parsed.state_of(Slot::B) == SlotState::Bad);
+        assert_eq!(parsed.boot_attempts, 2);
+    }
+
+    #[test]
+    fn parse_rejects_wrong_magic() {
+        let mut bytes = [0u8; BootState::SIZE];
+        state(Slot::A, SlotState::Good, SlotState::Good, 0).serialize(&mut bytes);
+        bytes[0] ^= 0xFF;
+        assert!(BootState::parse(&bytes).is_none());
+    }
+}
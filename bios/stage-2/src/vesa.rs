@@ -0,0 +1,161 @@
+//! Real-mode VESA BIOS Extensions (VBE) framebuffer setup via `INT 0x10`.
+
+use byteorder::{ByteOrder, LittleEndian};
+use core::arch::asm;
+
+const VBE_FUNCTION_CONTROLLER_INFO: u16 = 0x4F00;
+const VBE_FUNCTION_MODE_INFO: u16 = 0x4F01;
+const VBE_FUNCTION_SET_MODE: u16 = 0x4F02;
+const VBE_SUCCESS: u16 = 0x004F;
+const LINEAR_FRAMEBUFFER_BIT: u16 = 0x4000;
+const LIST_TERMINATOR: u16 = 0xFFFF;
+
+const INFO_BLOCK_SIZE: usize = 512;
+const MODE_INFO_BLOCK_SIZE: usize = 256;
+
+/// The framebuffer mode stage 2 asks the BIOS for. Kept as a constant
+/// (rather than hardcoded inside [`set_requested_mode`]) so it is easy to
+/// change in one place, or eventually plumb through from `build.rs`.
+pub const REQUESTED_MODE: RequestedMode = RequestedMode {
+    width: 1280,
+    height: 800,
+    bits_per_pixel: 32,
+};
+
+pub struct RequestedMode {
+    pub width: u16,
+    pub height: u16,
+    pub bits_per_pixel: u8,
+}
+
+/// The framebuffer layout handed off to stage 3, once a mode has been set.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FramebufferInfo {
+    pub address: u64,
+    pub width: u16,
+    pub height: u16,
+    pub pitch: u32,
+    pub bytes_per_pixel: u8,
+}
+
+/// Enumerates VBE modes (`AX=0x4F00` for the controller info block holding
+/// the mode list, then `AX=0x4F01` per mode), sets the first
+/// linear-framebuffer mode matching [`REQUESTED_MODE`] with `AX=0x4F02`,
+/// and returns its resulting layout. Returns `None` if the BIOS has no VBE
+/// support or no matching mode.
+pub fn set_requested_mode() -> Option<FramebufferInfo> {
+    let mut info_block = [0u8; INFO_BLOCK_SIZE];
+    info_block[0..4].copy_from_slice(b"VBE2");
+    if vbe_call(VBE_FUNCTION_CONTROLLER_INFO, 0, info_block.as_mut_ptr() as u32) != VBE_SUCCESS {
+        return None;
+    }
+
+    let mode_list = real_mode_ptr(
+        LittleEndian::read_u16(&info_block[16..18]),
+        LittleEndian::read_u16(&info_block[14..16]),
+    ) as *const u16;
+
+    let mut mode_idx = 0isize;
+    loop {
+        let mode = unsafe { *mode_list.offset(mode_idx) };
+        if mode == LIST_TERMINATOR {
+            return None;
+        }
+        mode_idx += 1;
+
+        if let Some(framebuffer) = try_mode(mode) {
+            return Some(framebuffer);
+        }
+    }
+}
+
+fn try_mode(mode: u16) -> Option<FramebufferInfo> {
+    let mut mode_info = [0u8; MODE_INFO_BLOCK_SIZE];
+    if vbe_call(
+        VBE_FUNCTION_MODE_INFO,
+        u32::from(mode),
+        mode_info.as_mut_ptr() as u32,
+    ) != VBE_SUCCESS
+    {
+        return None;
+    }
+
+    let attributes = LittleEndian::read_u16(&mode_info[0..2]);
+    let has_linear_framebuffer = attributes & 0x80 != 0;
+    let width = LittleEndian::read_u16(&mode_info[18..20]);
+    let height = LittleEndian::read_u16(&mode_info[20..22]);
+    let bits_per_pixel = mode_info[25];
+
+    if !has_linear_framebuffer
+        || width != REQUESTED_MODE.width
+        || height != REQUESTED_MODE.height
+        || bits_per_pixel != REQUESTED_MODE.bits_per_pixel
+    {
+        return None;
+    }
+
+    // Request the linear framebuffer model (bit 14) for this mode.
+    if vbe_set_mode(mode | LINEAR_FRAMEBUFFER_BIT) != VBE_SUCCESS {
+        return None;
+    }
+
+    Some(FramebufferInfo {
+        address: u64::from(LittleEndian::read_u32(&mode_info[40..44])),
+        width,
+        height,
+        // `BytesPerScanLine` (offset 16) describes the banked/windowed
+        // model; we requested the linear-framebuffer model above, which
+        // can use a different pitch reported separately as
+        // `LinBytesPerScanLine` (offset 50).
+        pitch: u32::from(LittleEndian::read_u16(&mode_info[50..52])),
+        bytes_per_pixel: bits_per_pixel / 8,
+    })
+}
+
+/// Converts a real-mode segment:offset far pointer into a flat address,
+/// valid under unreal mode's zero-based segments.
+fn real_mode_ptr(segment: u16, offset: u16) -> u32 {
+    u32::from(segment) * 16 + u32::from(offset)
+}
+
+/// Issues a VBE call that takes its argument (mode number) in `cx` and
+/// writes its result into a buffer pointed to by `edi`; this is the
+/// calling convention `VBE_FUNCTION_CONTROLLER_INFO` and
+/// `VBE_FUNCTION_MODE_INFO` use. `VBE_FUNCTION_SET_MODE` instead wants its
+/// mode number in `bx` with no output buffer, so it goes through
+/// [`vbe_set_mode`] rather than this function.
+fn vbe_call(function: u16, input: u32, buffer_ptr: u32) -> u16 {
+    let result: u16;
+    unsafe {
+        asm!(
+            "int 0x10",
+            inout("ax") function => result,
+            in("cx") input as u16,
+            in("edi") buffer_ptr,
+        );
+    }
+    result
+}
+
+/// Issues `VBE_FUNCTION_SET_MODE` (`AX=0x4F02`), which takes the mode
+/// number (with the linear-framebuffer bit already set, if requested) in
+/// `bx` rather than `cx`.
+fn vbe_set_mode(mode: u16) -> u16 {
+    let result: u16;
+    unsafe {
+        asm!(
+            // LLVM reserves `ebx` internally and won't accept it as a
+            // direct inline-asm operand (the same restriction worked
+            // around in `memory_map.rs`), so shuttle the mode number
+            // through it manually instead.
+            "push ebx",
+            "mov bx, {mode:x}",
+            "int 0x10",
+            "pop ebx",
+            inout("ax") VBE_FUNCTION_SET_MODE => result,
+            mode = in(reg) mode,
+        );
+    }
+    result
+}
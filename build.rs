@@ -7,6 +7,11 @@ use std::path::{Path, PathBuf};
 const BOOTLOADER_VERSION: &str = env!("CARGO_PKG_VERSION");
 const BOOTLOADER_REPO: &str = "https://github.com/azyklus/springboard";
 
+/// When the `embed-binaries` feature is enabled, the built BIOS/UEFI binaries
+/// are additionally written into `OUT_DIR` as `&'static [u8]` constants, so
+/// that `src/lib.rs` can `include!` them and callers can assemble images
+/// in-memory without locating the build output themselves.
+
 fn main() {
     #[cfg(not(feature = "uefi"))]
     async fn uefiMain() {}
@@ -57,6 +62,16 @@ async fn biosMain() {
         "cargo:rustc-env=BIOS_STAGE_4_PATH={}",
         bios_stage_4_path.display()
     );
+
+    #[cfg(not(docsrs_dummy_build))]
+    #[cfg(feature = "embed-binaries")]
+    writeEmbeddedBiosModule(
+        &out_dir,
+        &bios_boot_sector_path,
+        &bios_stage_2_path,
+        &bios_stage_3_path,
+        &bios_stage_4_path,
+    );
 }
 
 #[cfg(feature = "uefi")]
@@ -73,6 +88,10 @@ async fn uefiMain() {
         "cargo:rustc-env=UEFI_BOOTLOADER_PATH={}",
         uefi_path.display()
     );
+
+    #[cfg(not(docsrs_dummy_build))]
+    #[cfg(feature = "embed-binaries")]
+    writeEmbeddedUefiModule(&out_dir, &uefi_path);
 }
 
 #[cfg(not(docsrs_dummy_build))]
@@ -316,3 +335,49 @@ async fn convertElfBin(elf_path: PathBuf) -> PathBuf {
     }
     flat_binary_path
 }
+
+/// Writes `$OUT_DIR/bios_embedded.rs`, a generated module that embeds the
+/// flattened BIOS stage binaries as `&'static [u8]` constants via
+/// `include_bytes!`. `src/lib.rs` pulls this in with `include!` when the
+/// `embed-binaries` feature is enabled.
+#[cfg(feature = "bios")]
+#[cfg(feature = "embed-binaries")]
+fn writeEmbeddedBiosModule(
+    out_dir: &Path,
+    boot_sector_path: &Path,
+    stage_2_path: &Path,
+    stage_3_path: &Path,
+    stage_4_path: &Path,
+) {
+    let source = format!(
+        "/// The BIOS boot sector, embedded at build time.\n\
+         pub static BOOT_SECTOR: &[u8] = include_bytes!({boot_sector:?});\n\
+         /// The BIOS second stage, embedded at build time.\n\
+         pub static STAGE_2: &[u8] = include_bytes!({stage_2:?});\n\
+         /// The BIOS third stage, embedded at build time.\n\
+         pub static STAGE_3: &[u8] = include_bytes!({stage_3:?});\n\
+         /// The BIOS fourth stage, embedded at build time.\n\
+         pub static STAGE_4: &[u8] = include_bytes!({stage_4:?});\n",
+        boot_sector = boot_sector_path,
+        stage_2 = stage_2_path,
+        stage_3 = stage_3_path,
+        stage_4 = stage_4_path,
+    );
+    std::fs::write(out_dir.join("bios_embedded.rs"), source)
+       .expect("failed to write generated bios_embedded.rs");
+}
+
+/// Writes `$OUT_DIR/uefi_embedded.rs`, a generated module that embeds the
+/// UEFI bootloader `.efi` image as a `&'static [u8]` constant, mirroring
+/// [`writeEmbeddedBiosModule`].
+#[cfg(feature = "uefi")]
+#[cfg(feature = "embed-binaries")]
+fn writeEmbeddedUefiModule(out_dir: &Path, uefi_path: &Path) {
+    let source = format!(
+        "/// The UEFI bootloader image, embedded at build time.\n\
+         pub static BOOTLOADER: &[u8] = include_bytes!({uefi_path:?});\n",
+        uefi_path = uefi_path,
+    );
+    std::fs::write(out_dir.join("uefi_embedded.rs"), source)
+       .expect("failed to write generated uefi_embedded.rs");
+}
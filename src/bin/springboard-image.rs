@@ -0,0 +1,26 @@
+//! Thin CLI around [`springboard::image::build_disk_image`].
+//!
+//! ```text
+//! springboard-image <manifest.toml> <out-image>
+//! ```
+
+#[cfg(feature = "image-builder")]
+fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let (Some(manifest), Some(out_path)) = (args.next(), args.next()) else {
+        eprintln!("usage: springboard-image <manifest.toml> <out-image>");
+        std::process::exit(2);
+    };
+
+    if let Err(error) = springboard::image::build_disk_image(manifest.as_ref(), out_path.as_ref())
+    {
+        eprintln!("error: {error}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "image-builder"))]
+fn main() {
+    eprintln!("springboard-image requires the `image-builder` feature");
+    std::process::exit(2);
+}
@@ -0,0 +1,173 @@
+//! Assembles a bootable disk image from a parsed [`Manifest`].
+
+use std::fs::File;
+use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::checksum;
+use super::error::BuildError;
+use super::manifest::{FileRole, Manifest, PartitionKind, PartitionManifest};
+use super::partition_table;
+
+const SECTOR_SIZE: u64 = 512;
+
+/// Assembles a bootable disk image at `out_path` from the manifest at
+/// `manifest_path`. Source paths inside the manifest are resolved
+/// relative to the manifest's own directory.
+pub fn build_disk_image(manifest_path: &Path, out_path: &Path) -> Result<(), BuildError> {
+    let text = std::fs::read_to_string(manifest_path)
+        .map_err(|source| BuildError::ReadManifest { source })?;
+    let manifest: Manifest =
+        toml::from_str(&text).map_err(|source| BuildError::ParseManifest { source })?;
+    let base_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    build_disk_image_from_manifest(&manifest, base_dir, out_path)
+}
+
+fn build_disk_image_from_manifest(
+    manifest: &Manifest,
+    base_dir: &Path,
+    out_path: &Path,
+) -> Result<(), BuildError> {
+    let first_usable_lba = partition_table::first_usable_lba(manifest.disk.scheme);
+    let total_size: u64 = manifest.partitions.iter().map(|p| p.size).sum();
+
+    let mut image = File::create(out_path).map_err(|source| BuildError::WriteImage { source })?;
+    image
+        .set_len(first_usable_lba * SECTOR_SIZE + total_size)
+        .map_err(|source| BuildError::WriteImage { source })?;
+
+    partition_table::write(&mut image, manifest.disk.scheme, &manifest.partitions)
+        .map_err(|source| BuildError::WriteImage { source })?;
+
+    let mut offset = first_usable_lba * SECTOR_SIZE;
+    let mut wrote_stages = false;
+    for partition in &manifest.partitions {
+        match partition.kind {
+            PartitionKind::BootloaderStage => {
+                write_bootloader_stage_partition(&mut image, offset, partition)?
+            }
+            PartitionKind::Data => {
+                let include_stages = !wrote_stages;
+                write_data_partition(&mut image, offset, partition, base_dir, include_stages)?;
+                wrote_stages |= include_stages;
+            }
+        }
+        offset += partition.size;
+    }
+
+    Ok(())
+}
+
+/// Writes the embedded second-stage binary (see the `embed-binaries`
+/// feature) into the bootloader-stage partition. Only stage 2 itself
+/// lives here: `gpt::find_partitions`/`mbr_partition_offsets` locate this
+/// partition purely to hand stage 2 control, and stage 2 then loads
+/// `boot-stage-3`/`boot-stage-4` as ordinary FAT files from the data
+/// partition, the same way it loads the kernel -- see
+/// [`write_data_partition`].
+fn write_bootloader_stage_partition(
+    image: &mut File,
+    offset: u64,
+    partition: &PartitionManifest,
+) -> Result<(), BuildError> {
+    #[cfg(all(feature = "bios", feature = "embed-binaries"))]
+    let stage_2: &[u8] = crate::bios::STAGE_2;
+    #[cfg(not(all(feature = "bios", feature = "embed-binaries")))]
+    let stage_2: &[u8] = &[];
+
+    assert!(
+        stage_2.len() as u64 <= partition.size,
+        "bootloader-stage partition is too small for the embedded stage 2 binary"
+    );
+
+    image
+        .seek(SeekFrom::Start(offset))
+        .map_err(|source| BuildError::WriteImage { source })?;
+    image
+        .write_all(stage_2)
+        .map_err(|source| BuildError::WriteImage { source })
+}
+
+/// Formats a FAT partition with `fatfs`, copies in the manifest's files,
+/// adds the embedded `boot-stage-3`/`boot-stage-4` binaries stage 2 loads
+/// by that exact name, and writes a `checksums` manifest covering all of
+/// them plus every `kernel`/`ramdisk` file so the BIOS second stage can
+/// verify them (see `bios/stage-2/src/manifest.rs`).
+///
+/// Only the first data partition in a manifest gets the embedded stages;
+/// a manifest with more than one `data` partition is assumed to use the
+/// others purely for user files.
+fn write_data_partition(
+    image: &mut File,
+    offset: u64,
+    partition: &PartitionManifest,
+    base_dir: &Path,
+    include_stages: bool,
+) -> Result<(), BuildError> {
+    let mut partition_data = vec![0u8; usize::try_from(partition.size).unwrap()];
+    fatfs::format_volume(
+        Cursor::new(&mut partition_data[..]),
+        fatfs::FormatVolumeOptions::new(),
+    )
+    .map_err(|source| BuildError::FormatFat { source })?;
+
+    let fs = fatfs::FileSystem::new(Cursor::new(&mut partition_data[..]), fatfs::FsOptions::new())
+        .map_err(|source| BuildError::FormatFat { source })?;
+
+    let mut checksums = Vec::new();
+    {
+        let root = fs.root_dir();
+        for file in &partition.files {
+            let contents = std::fs::read(base_dir.join(&file.source))
+                .map_err(|source| BuildError::ReadSourceFile { source })?;
+
+            let mut out = root
+                .create_file(file.dest.trim_start_matches('/'))
+                .map_err(|source| BuildError::FormatFat { source })?;
+            out.write_all(&contents)
+                .map_err(|source| BuildError::FormatFat { source })?;
+
+            if file.role != FileRole::Data {
+                checksums.push((
+                    file.dest.trim_start_matches('/').to_string(),
+                    checksum::checksum(&contents),
+                ));
+            }
+        }
+
+        if include_stages {
+            #[cfg(all(feature = "bios", feature = "embed-binaries"))]
+            let stages: &[(&str, &[u8])] = &[
+                ("boot-stage-3", crate::bios::STAGE_3),
+                ("boot-stage-4", crate::bios::STAGE_4),
+            ];
+            #[cfg(not(all(feature = "bios", feature = "embed-binaries")))]
+            let stages: &[(&str, &[u8])] = &[];
+
+            for (name, contents) in stages {
+                let mut out = root
+                    .create_file(name)
+                    .map_err(|source| BuildError::FormatFat { source })?;
+                out.write_all(contents)
+                    .map_err(|source| BuildError::FormatFat { source })?;
+                checksums.push((name.to_string(), checksum::checksum(contents)));
+            }
+        }
+
+        if !checksums.is_empty() {
+            let mut out = root
+                .create_file("checksums")
+                .map_err(|source| BuildError::FormatFat { source })?;
+            out.write_all(&checksum::build_manifest(&checksums))
+                .map_err(|source| BuildError::FormatFat { source })?;
+        }
+    }
+    drop(fs);
+
+    image
+        .seek(SeekFrom::Start(offset))
+        .map_err(|source| BuildError::WriteImage { source })?;
+    image
+        .write_all(&partition_data)
+        .map_err(|source| BuildError::WriteImage { source })
+}
@@ -0,0 +1,36 @@
+//! Error type returned by [`super::build_disk_image`].
+
+use std::io;
+
+use thiserror::Error;
+
+/// Everything that can go wrong while assembling a disk image from a
+/// manifest.
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("failed to read disk image manifest")]
+    ReadManifest {
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to parse disk image manifest")]
+    ParseManifest {
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("failed to read a file referenced by the manifest")]
+    ReadSourceFile {
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to format a FAT partition")]
+    FormatFat {
+        #[source]
+        source: fatfs::Error<io::Error>,
+    },
+    #[error("failed to write the disk image")]
+    WriteImage {
+        #[source]
+        source: io::Error,
+    },
+}
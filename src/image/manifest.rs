@@ -0,0 +1,165 @@
+//! The TOML manifest format consumed by [`super::build_disk_image`].
+//!
+//! ```toml
+//! [disk]
+//! scheme = "gpt"
+//!
+//! [[partition]]
+//! kind = "bootloader-stage"
+//! size = "1MiB"
+//!
+//! [[partition]]
+//! kind = "data"
+//! size = "64MiB"
+//!
+//! [[partition.files]]
+//! role = "kernel"
+//! source = "target/x86_64/release/my-kernel"
+//! dest = "/kernel-x86_64-a"
+//!
+//! [[partition.files]]
+//! role = "ramdisk"
+//! source = "assets/ramdisk.img"
+//! dest = "/ramdisk"
+//! ```
+//!
+//! A kernel's `dest` must match the name `bios/stage-2/src/update.rs`
+//! looks up: `kernel-x86_64-a`/`kernel-x86_64-b` on a disk with A/B boot
+//! state, or the bare `kernel-x86_64` on one without. `boot-stage-3` and
+//! `boot-stage-4` need no manifest entry -- the first `data` partition
+//! gets them automatically from the embedded stage binaries.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// A parsed disk-image manifest.
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub disk: DiskManifest,
+    #[serde(default, rename = "partition")]
+    pub partitions: Vec<PartitionManifest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiskManifest {
+    pub scheme: PartitionScheme,
+}
+
+/// Which partition table format to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionScheme {
+    Mbr,
+    Gpt,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PartitionManifest {
+    pub kind: PartitionKind,
+    /// Partition size, e.g. `"64MiB"` or a raw byte count.
+    #[serde(deserialize_with = "deserialize_size")]
+    pub size: u64,
+    #[serde(default)]
+    pub files: Vec<FileManifest>,
+}
+
+/// What a partition is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PartitionKind {
+    /// Holds the embedded second-stage binary (stages 3 and 4 go into the
+    /// first data partition instead), matching
+    /// `BOOTLOADER_SECOND_STAGE_PARTITION_TYPE` in `bios/stage-2`.
+    BootloaderStage,
+    /// A FAT data partition populated with [`FileManifest`] entries.
+    Data,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileManifest {
+    pub source: PathBuf,
+    pub dest: String,
+    #[serde(default)]
+    pub role: FileRole,
+}
+
+/// Tags a file within a data partition so the builder knows to record it
+/// (kernel, ramdisk) for integrity-manifest generation, or to just copy it
+/// (data) with no further bookkeeping.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileRole {
+    Kernel,
+    Ramdisk,
+    #[default]
+    Data,
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SizeOrString {
+        Size(u64),
+        String(String),
+    }
+
+    match SizeOrString::deserialize(deserializer)? {
+        SizeOrString::Size(bytes) => Ok(bytes),
+        SizeOrString::String(text) => parse_size(&text).map_err(D::Error::custom),
+    }
+}
+
+fn parse_size(text: &str) -> Result<u64, String> {
+    const UNITS: &[(&str, u64)] = &[
+        ("GiB", 1024 * 1024 * 1024),
+        ("MiB", 1024 * 1024),
+        ("KiB", 1024),
+        ("B", 1),
+    ];
+    let text = text.trim();
+    for (suffix, multiplier) in UNITS {
+        if let Some(number) = text.strip_suffix(suffix) {
+            let number: u64 = number
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid size {text:?}"))?;
+            return Ok(number * multiplier);
+        }
+    }
+    text.parse().map_err(|_| format!("invalid size {text:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_unit_suffix() {
+        assert_eq!(parse_size("64MiB").unwrap(), 64 * 1024 * 1024);
+        assert_eq!(parse_size("1GiB").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(parse_size("512KiB").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("128B").unwrap(), 128);
+    }
+
+    #[test]
+    fn parses_a_bare_byte_count() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+    }
+
+    #[test]
+    fn tolerates_whitespace_around_the_number() {
+        assert_eq!(parse_size(" 64 MiB ").unwrap(), 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse_size("not-a-size").is_err());
+        assert!(parse_size("").is_err());
+    }
+}
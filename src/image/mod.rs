@@ -0,0 +1,16 @@
+//! Declarative, manifest-driven disk image assembly.
+//!
+//! This replaces hand-wiring the stage artifacts produced by `build.rs`:
+//! describe the partition scheme, each partition's type and size, and the
+//! files to place into it in a TOML manifest (see [`manifest::Manifest`]),
+//! then call [`build_disk_image`] to get a bootable image out.
+
+mod builder;
+mod checksum;
+mod error;
+mod manifest;
+mod partition_table;
+
+pub use builder::build_disk_image;
+pub use error::BuildError;
+pub use manifest::{DiskManifest, FileManifest, FileRole, Manifest, PartitionKind, PartitionManifest, PartitionScheme};
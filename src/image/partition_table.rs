@@ -0,0 +1,153 @@
+//! Writes the MBR or GPT partition table describing the layout declared
+//! in the manifest.
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::checksum;
+use super::manifest::{PartitionKind, PartitionManifest, PartitionScheme};
+
+const SECTOR_SIZE: u64 = 512;
+
+/// Same value as `BOOTLOADER_SECOND_STAGE_PARTITION_TYPE` in
+/// `bios/stage-2/src/main.rs`.
+const BOOTLOADER_SECOND_STAGE_MBR_TYPE: u8 = 0x20;
+/// Same bytes as `FAT_PARTITION_TYPE` there is none on the MBR side (FAT
+/// already has standard MBR type bytes); we use the common FAT32 LBA type.
+const FAT_MBR_TYPE: u8 = 0x0C;
+
+/// Same bytes as `gpt::BOOTLOADER_SECOND_STAGE_PARTITION_TYPE` in
+/// `bios/stage-2/src/gpt.rs` (the standard "BIOS boot partition" GUID).
+const BOOTLOADER_SECOND_STAGE_GPT_TYPE: [u8; 16] = [
+    0x48, 0x61, 0x68, 0x21, 0x49, 0x64, 0x6F, 0x6E, 0x74, 0x4E, 0x65, 0x65, 0x64, 0x45, 0x46, 0x49,
+];
+/// Same bytes as `gpt::FAT_PARTITION_TYPE` there (Microsoft Basic Data
+/// Partition GUID).
+const FAT_GPT_TYPE: [u8; 16] = [
+    0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+];
+
+/// The first sector usable by a partition under the given scheme: LBA 1
+/// for MBR (right after the boot sector), LBA 34 for GPT (after the
+/// protective MBR, header and a 128-entry partition array).
+pub fn first_usable_lba(scheme: PartitionScheme) -> u64 {
+    match scheme {
+        PartitionScheme::Mbr => 1,
+        PartitionScheme::Gpt => 34,
+    }
+}
+
+pub fn write(
+    image: &mut File,
+    scheme: PartitionScheme,
+    partitions: &[PartitionManifest],
+) -> io::Result<()> {
+    match scheme {
+        PartitionScheme::Mbr => write_mbr(image, partitions),
+        PartitionScheme::Gpt => write_gpt(image, partitions),
+    }
+}
+
+/// A 512-byte sector preloaded with the embedded stage-1 boot sector's
+/// code area (everything but the partition table and `0x55AA` signature,
+/// which the caller overlays afterwards), so the produced image actually
+/// has something at LBA 0 to load stage 2. Without the `bios` and
+/// `embed-binaries` features there is no stage-1 binary to embed, so the
+/// code area is left zeroed and the image isn't bootable on its own.
+fn boot_sector_code_area() -> [u8; SECTOR_SIZE as usize] {
+    let mut sector = [0u8; SECTOR_SIZE as usize];
+    #[cfg(all(feature = "bios", feature = "embed-binaries"))]
+    {
+        let code = crate::bios::BOOT_SECTOR;
+        let len = code.len().min(446);
+        sector[..len].copy_from_slice(&code[..len]);
+    }
+    sector
+}
+
+fn write_mbr(image: &mut File, partitions: &[PartitionManifest]) -> io::Result<()> {
+    assert!(
+        partitions.len() <= 4,
+        "a legacy MBR supports at most 4 partitions"
+    );
+
+    let mut sector = boot_sector_code_area();
+    let mut lba = first_usable_lba(PartitionScheme::Mbr);
+    for (idx, partition) in partitions.iter().enumerate() {
+        let entry = &mut sector[446 + idx * 16..446 + (idx + 1) * 16];
+        entry[4] = match partition.kind {
+            PartitionKind::BootloaderStage => BOOTLOADER_SECOND_STAGE_MBR_TYPE,
+            PartitionKind::Data => FAT_MBR_TYPE,
+        };
+        let sectors = partition.size / SECTOR_SIZE;
+        LittleEndian::write_u32(&mut entry[8..12], u32::try_from(lba).unwrap());
+        LittleEndian::write_u32(&mut entry[12..16], u32::try_from(sectors).unwrap());
+        lba += sectors;
+    }
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+
+    image.seek(SeekFrom::Start(0))?;
+    image.write_all(&sector)
+}
+
+fn write_gpt(image: &mut File, partitions: &[PartitionManifest]) -> io::Result<()> {
+    let total_sectors =
+        first_usable_lba(PartitionScheme::Gpt) + partitions.iter().map(|p| p.size / SECTOR_SIZE).sum::<u64>();
+
+    let mut protective_mbr = boot_sector_code_area();
+    let entry = &mut protective_mbr[446..462];
+    entry[4] = 0xEE; // GPT protective type
+    LittleEndian::write_u32(&mut entry[8..12], 1);
+    LittleEndian::write_u32(&mut entry[12..16], u32::try_from(total_sectors.saturating_sub(1)).unwrap_or(u32::MAX));
+    protective_mbr[510] = 0x55;
+    protective_mbr[511] = 0xAA;
+    image.seek(SeekFrom::Start(0))?;
+    image.write_all(&protective_mbr)?;
+
+    const ENTRIES_LBA: u64 = 2;
+    const NUM_ENTRIES: u32 = 128;
+    const ENTRY_SIZE: u32 = 128;
+
+    let mut entries = vec![0u8; (NUM_ENTRIES * ENTRY_SIZE) as usize];
+    let mut lba = first_usable_lba(PartitionScheme::Gpt);
+    for (idx, partition) in partitions.iter().enumerate() {
+        let entry = &mut entries[idx * ENTRY_SIZE as usize..(idx + 1) * ENTRY_SIZE as usize];
+        let type_guid = match partition.kind {
+            PartitionKind::BootloaderStage => BOOTLOADER_SECOND_STAGE_GPT_TYPE,
+            PartitionKind::Data => FAT_GPT_TYPE,
+        };
+        entry[0..16].copy_from_slice(&type_guid);
+        let sectors = partition.size / SECTOR_SIZE;
+        LittleEndian::write_u64(&mut entry[32..40], lba);
+        LittleEndian::write_u64(&mut entry[40..48], lba + sectors - 1);
+        lba += sectors;
+    }
+    let entries_crc = checksum::checksum(&entries);
+
+    let mut header = [0u8; 92];
+    header[0..8].copy_from_slice(b"EFI PART");
+    LittleEndian::write_u32(&mut header[8..12], 0x0001_0000); // revision 1.0
+    LittleEndian::write_u32(&mut header[12..16], header.len() as u32);
+    LittleEndian::write_u64(&mut header[24..32], 1); // this header's own LBA
+    LittleEndian::write_u64(&mut header[40..48], first_usable_lba(PartitionScheme::Gpt));
+    LittleEndian::write_u64(&mut header[48..56], total_sectors.saturating_sub(1));
+    LittleEndian::write_u64(&mut header[72..80], ENTRIES_LBA);
+    LittleEndian::write_u32(&mut header[80..84], NUM_ENTRIES);
+    LittleEndian::write_u32(&mut header[84..88], ENTRY_SIZE);
+    LittleEndian::write_u32(&mut header[88..92], entries_crc);
+    // header[16..20] (the header's own CRC) is computed last, over the
+    // header with that field held at zero, matching `gpt::find_partitions`.
+    let header_crc = checksum::checksum(&header);
+    LittleEndian::write_u32(&mut header[16..20], header_crc);
+
+    let mut header_sector = [0u8; SECTOR_SIZE as usize];
+    header_sector[..header.len()].copy_from_slice(&header);
+    image.seek(SeekFrom::Start(SECTOR_SIZE))?;
+    image.write_all(&header_sector)?;
+
+    image.seek(SeekFrom::Start(ENTRIES_LBA * SECTOR_SIZE))?;
+    image.write_all(&entries)
+}
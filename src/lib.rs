@@ -0,0 +1,37 @@
+//! Build-output glue for the springboard bootloader.
+//!
+//! `build.rs` compiles the BIOS/UEFI bootloader stages out-of-tree and
+//! exposes their locations via `cargo:rustc-env` variables
+//! (`BIOS_STAGE_*_PATH`, `UEFI_BOOTLOADER_PATH`). Enable the
+//! `embed-binaries` feature to additionally embed the built binaries
+//! directly into this crate as `&'static [u8]` constants, so consumers
+//! can assemble a bootable image entirely in-memory without locating or
+//! retaining the build output themselves.
+//!
+//! Enable the `image-builder` feature for [`image::build_disk_image`], a
+//! manifest-driven way to assemble a full bootable disk image on the
+//! host; it pulls in `std` and is off by default for size-sensitive
+//! no_std consumers.
+
+#![cfg_attr(not(feature = "image-builder"), no_std)]
+
+/// Manifest-driven disk image assembly, available with the
+/// `image-builder` feature.
+#[cfg(feature = "image-builder")]
+pub mod image;
+
+/// Embedded BIOS stage binaries, available when built with the `bios` and
+/// `embed-binaries` features.
+#[cfg(feature = "bios")]
+#[cfg(feature = "embed-binaries")]
+pub mod bios {
+    include!(concat!(env!("OUT_DIR"), "/bios_embedded.rs"));
+}
+
+/// The embedded UEFI bootloader image, available when built with the
+/// `uefi` and `embed-binaries` features.
+#[cfg(feature = "uefi")]
+#[cfg(feature = "embed-binaries")]
+pub mod uefi {
+    include!(concat!(env!("OUT_DIR"), "/uefi_embedded.rs"));
+}